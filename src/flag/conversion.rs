@@ -0,0 +1,201 @@
+use std::str::FromStr;
+use std::fmt;
+
+/// Describes how the raw text captured by a flag should be interpreted
+/// once parsing has finished. This mirrors the shape of Vector's
+/// `Conversion` enum: the crate stores the raw text during parsing and
+/// only applies the conversion afterwards, so a bad value can be
+/// reported alongside the key and the text that failed to convert.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Conversion {
+    AsIs,
+    Integer,
+    Float,
+    Boolean,
+    Timestamp,
+    TimestampFmt(String),
+    TimestampTzFmt(String)
+}
+
+#[derive(Debug)]
+pub struct ConversionParseError {
+    raw: String
+}
+
+impl fmt::Display for ConversionParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown conversion \"{}\"", self.raw)
+    }
+}
+
+impl FromStr for Conversion {
+    type Err = ConversionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(fmt) = s.strip_prefix("timestamp|") {
+            return Ok(Conversion::TimestampFmt(fmt.to_string()));
+        }
+        if let Some(fmt) = s.strip_prefix("timestamptz|") {
+            return Ok(Conversion::TimestampTzFmt(fmt.to_string()));
+        }
+        match s {
+            "asis" | "bytes" | "string" => Ok(Conversion::AsIs),
+            "int" | "integer" => Ok(Conversion::Integer),
+            "float" => Ok(Conversion::Float),
+            "bool" | "boolean" => Ok(Conversion::Boolean),
+            "timestamp" => Ok(Conversion::Timestamp),
+            _ => Err(ConversionParseError { raw: s.to_string() })
+        }
+    }
+}
+
+fn parse_bool(raw: &str) -> Result<bool, String> {
+    match raw {
+        "true" | "t" | "1" => Ok(true),
+        "false" | "f" | "0" => Ok(false),
+        _ => Err(format!("\"{}\" is not a boolean", raw))
+    }
+}
+
+/// A hand-rolled stand-in for a full RFC3339 parser: the crate has no
+/// external dependencies, so this checks the shape
+/// (`YYYY-MM-DDTHH:MM:SS`, with `T` or a space as the separator)
+/// rather than pulling in a date/time crate just for this one
+/// conversion. Only the digits before a `.` in the seconds are checked;
+/// a fractional part and whatever follows the first `Z`/`+`/`-` after
+/// the date (the offset) are not validated.
+fn looks_like_rfc3339(raw: &str) -> Result<(), String> {
+    let bytes = raw.as_bytes();
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if bytes.len() < 19 {
+        return Err(format!("\"{}\" is too short to be an RFC3339 timestamp", raw));
+    }
+    if !raw.is_char_boundary(10) {
+        return Err(format!("\"{}\" does not look like YYYY-MM-DD", raw));
+    }
+    let (date, rest) = raw.split_at(10);
+    let mut date_parts = date.split('-');
+    match (date_parts.next(), date_parts.next(), date_parts.next(), date_parts.next()) {
+        (Some(y), Some(m), Some(d), None) if digits(y) && digits(m) && digits(d) => {},
+        _ => return Err(format!("\"{}\" does not look like YYYY-MM-DD", date))
+    }
+    let rest = match rest.strip_prefix('T').or_else(|| rest.strip_prefix(' ')) {
+        Some(rest) => rest,
+        None => return Err(format!("\"{}\" is missing the date/time separator", raw))
+    };
+    let time_end = rest.find(['Z', '+', '-']).unwrap_or(rest.len());
+    let time = &rest[..time_end];
+    let mut time_parts = time.splitn(3, ':');
+    match (time_parts.next(), time_parts.next(), time_parts.next()) {
+        (Some(h), Some(mi), Some(s)) if digits(h) && digits(mi)
+            && digits(s.split('.').next().unwrap_or("")) => Ok(()),
+        _ => Err(format!("\"{}\" does not look like HH:MM:SS", time))
+    }
+}
+
+/// Matches `raw` against a strftime-style `fmt` one token at a time.
+/// Only the handful of tokens this crate's callers actually use are
+/// supported (`%Y %m %d %H %M %S`); any other `%` token is rejected as
+/// unsupported, and every other character is matched literally.
+fn matches_strftime(raw: &str, fmt: &str) -> Result<(), String> {
+    let mut r = raw.chars().peekable();
+    let mut f = fmt.chars().peekable();
+    let take_digits = |r: &mut std::iter::Peekable<std::str::Chars>, n: usize| -> Result<(), String> {
+        for _ in 0..n {
+            match r.next() {
+                Some(c) if c.is_ascii_digit() => {},
+                _ => return Err(format!("\"{}\" does not match format \"{}\"", raw, fmt))
+            }
+        }
+        Ok(())
+    };
+    while let Some(fc) = f.next() {
+        if fc == '%' {
+            match f.next() {
+                Some('Y') => take_digits(&mut r, 4)?,
+                Some('m') | Some('d') | Some('H') | Some('M') | Some('S') => take_digits(&mut r, 2)?,
+                Some(other) => return Err(format!("unsupported format token %{}", other)),
+                None => return Err(format!("dangling \"%\" in format \"{}\"", fmt))
+            }
+        } else {
+            match r.next() {
+                Some(rc) if rc == fc => {},
+                _ => return Err(format!("\"{}\" does not match format \"{}\"", raw, fmt))
+            }
+        }
+    }
+    if r.peek().is_some() {
+        return Err(format!("\"{}\" has trailing characters not covered by format \"{}\"", raw, fmt));
+    }
+    Ok(())
+}
+
+impl Conversion {
+    /// Checks that `raw` is well-formed for this conversion, returning a
+    /// human-readable reason on failure. The actual value extraction
+    /// happens at read-site via `Value::as_i64`/`as_f64`/`as_bool`; this
+    /// runs right after parsing so a bad value is caught immediately
+    /// instead of failing silently the first time it's read.
+    pub fn validate(&self, raw: &str) -> Result<(), String> {
+        match self {
+            Conversion::AsIs => Ok(()),
+            Conversion::Integer => raw.parse::<i64>().map(|_| ()).map_err(|e| e.to_string()),
+            Conversion::Float => raw.parse::<f64>().map(|_| ()).map_err(|e| e.to_string()),
+            Conversion::Boolean => parse_bool(raw).map(|_| ()),
+            Conversion::Timestamp => looks_like_rfc3339(raw),
+            Conversion::TimestampFmt(fmt) => matches_strftime(raw, fmt),
+            Conversion::TimestampTzFmt(fmt) => matches_strftime(raw, fmt)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_conversion_names() {
+        assert_eq!("asis".parse::<Conversion>().unwrap(), Conversion::AsIs);
+        assert_eq!("bytes".parse::<Conversion>().unwrap(), Conversion::AsIs);
+        assert_eq!("int".parse::<Conversion>().unwrap(), Conversion::Integer);
+        assert_eq!("integer".parse::<Conversion>().unwrap(), Conversion::Integer);
+        assert_eq!("float".parse::<Conversion>().unwrap(), Conversion::Float);
+        assert_eq!("bool".parse::<Conversion>().unwrap(), Conversion::Boolean);
+        assert_eq!("boolean".parse::<Conversion>().unwrap(), Conversion::Boolean);
+        assert_eq!("timestamp".parse::<Conversion>().unwrap(), Conversion::Timestamp);
+        assert_eq!("timestamp|%Y-%m-%d".parse::<Conversion>().unwrap()
+            , Conversion::TimestampFmt("%Y-%m-%d".to_string()));
+    }
+
+    #[test]
+    fn rejects_unknown_conversion() {
+        assert!("nope".parse::<Conversion>().is_err());
+    }
+
+    #[test]
+    fn validates_bool_values() {
+        assert!(Conversion::Boolean.validate("true").is_ok());
+        assert!(Conversion::Boolean.validate("f").is_ok());
+        assert!(Conversion::Boolean.validate("1").is_ok());
+        assert!(Conversion::Boolean.validate("nope").is_err());
+    }
+
+    #[test]
+    fn validates_rfc3339_timestamps() {
+        assert!(Conversion::Timestamp.validate("2024-01-02T03:04:05Z").is_ok());
+        assert!(Conversion::Timestamp.validate("not-a-timestamp").is_err());
+    }
+
+    #[test]
+    fn validates_custom_timestamp_format() {
+        let conv = Conversion::TimestampFmt("%Y-%m-%d".to_string());
+        assert!(conv.validate("2024-01-02").is_ok());
+        assert!(conv.validate("01/02/2024").is_err());
+    }
+
+    #[test]
+    fn rejects_multibyte_timestamp_without_panicking() {
+        let raw = "012345678é-01-02T03:04:05Z";
+        assert!(Conversion::Timestamp.validate(raw).is_err());
+    }
+}
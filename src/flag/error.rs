@@ -0,0 +1,50 @@
+use std::fmt;
+
+/// Everything that can go wrong while turning `env::args()` into typed
+/// `Value`s. Carries whatever key/text was involved so the caller (or
+/// the `parse()` wrapper) can report something actionable instead of
+/// the process just vanishing with `process::exit(0)`.
+#[derive(Debug)]
+pub enum ParseError {
+    /// A registered flag was seen before the previous flag had consumed
+    /// all of its values.
+    UnmatchedParameterCount { arg: String },
+    /// `arg` looks like a flag (starts with `-`) but isn't registered,
+    /// isn't a valid bundle of short flags, and there's no flag
+    /// currently reading values to hand it to.
+    UnknownKey { arg: String },
+    /// A flag's raw text failed its registered `Conversion`, or a
+    /// multi-valued flag was given in `key=value` form.
+    Conversion { key: String, raw: String, reason: String }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnmatchedParameterCount { arg } => {
+                write!(f, "the parameters before the {} parameter are not matched", arg)
+            },
+            ParseError::UnknownKey { arg } => {
+                write!(f, "unknown parameter \"{}\"", arg)
+            },
+            ParseError::Conversion { key, raw, reason } => {
+                write!(f, "the \"{}\" parameter's value \"{}\" is invalid: {}"
+                    , key, raw, reason)
+            }
+        }
+    }
+}
+
+/// Error produced by `Value::try_as`/`Value::try_as_bool` when the raw
+/// text doesn't fit the requested type.
+#[derive(Debug)]
+pub struct ValueError {
+    pub raw: String,
+    pub reason: String
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "value \"{}\" is invalid: {}", self.raw, self.reason)
+    }
+}
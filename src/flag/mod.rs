@@ -4,6 +4,12 @@ use std::cell::{RefCell};
 use std::collections::{VecDeque, HashMap};
 use std::fmt;
 
+mod conversion;
+pub use conversion::Conversion;
+
+mod error;
+pub use error::{ParseError, ValueError};
+
 pub type RcValue = Rc<RefCell<String>>;
 
 #[derive(Clone)]
@@ -34,13 +40,38 @@ struct Item {
     value: ItemValue,
     desc: String,
     is: bool,
-    value_len: isize
+    value_len: isize,
+    conversion: Option<Conversion>
 }
 
 pub struct Flag {
     help: String,
     keys: HashMap<String, Item>,
-    is_warning: bool
+    is_warning: bool,
+    subcommands: HashMap<String, Command>
+}
+
+type CommandHandler = Box<dyn FnMut(&Flag)>;
+
+/// A named mode of a multi-mode binary (`cargo build`, `mytool serve`):
+/// it owns its own `Flag` so its options don't collide with the
+/// top-level ones, plus an optional handler run once its arguments have
+/// parsed successfully. Built through `Flag::subcommand`/`Flag::on`,
+/// driven through `Flag::dispatch`.
+pub struct Command {
+    name: String,
+    flag: Flag,
+    handler: Option<CommandHandler>
+}
+
+impl Command {
+    fn new(name: String) -> Self {
+        Self {
+            name: name,
+            flag: Flag::new(),
+            handler: None
+        }
+    }
 }
 
 pub struct Value {
@@ -53,6 +84,55 @@ impl Value {
             v: v
         }
     }
+
+    fn try_single(&self) -> Result<std::cell::Ref<'_, String>, ValueError> {
+        match &self.v {
+            ItemValue::Single(v) => Ok(v.borrow()),
+            ItemValue::Multi(_) => Err(ValueError {
+                raw: String::new(),
+                reason: "value is not single".to_string()
+            })
+        }
+    }
+
+    /// Reads the value as an `i64`. Only valid for a flag registered
+    /// with `reg_u32`/a numeric `Conversion::Integer`; the parsing pass
+    /// already validated the text, so this can't fail in practice.
+    pub fn as_i64(&self) -> i64 {
+        self.try_as::<i64>().unwrap_or_else(|e| panic(e))
+    }
+
+    /// Reads the value as an `f64`. See [`Value::as_i64`].
+    pub fn as_f64(&self) -> f64 {
+        self.try_as::<f64>().unwrap_or_else(|e| panic(e))
+    }
+
+    /// Reads the value as a `bool`, accepting `true`/`false`/`t`/`f`
+    /// /`1`/`0`. See [`Value::as_i64`].
+    pub fn as_bool(&self) -> bool {
+        self.try_as_bool().unwrap_or_else(|e| panic(e))
+    }
+
+    /// Fallible counterpart to `as_i64`/`as_f64`: parses the raw text as
+    /// `T`, mirroring the `read!` macro's logic but returning a
+    /// [`ValueError`] instead of aborting the process. Use this from
+    /// library code that needs to recover from a bad value.
+    pub fn try_as<T: std::str::FromStr>(&self) -> Result<T, ValueError>
+        where T::Err: std::fmt::Display {
+        let raw = self.try_single()?;
+        raw.parse::<T>().map_err(|e| ValueError { raw: raw.clone(), reason: e.to_string() })
+    }
+
+    /// Fallible counterpart to `as_bool`, accepting the same
+    /// `true`/`false`/`t`/`f`/`1`/`0` spellings as [`Conversion::Boolean`].
+    pub fn try_as_bool(&self) -> Result<bool, ValueError> {
+        let raw = self.try_single()?;
+        match raw.as_str() {
+            "true" | "t" | "1" => Ok(true),
+            "false" | "f" | "0" => Ok(false),
+            v => Err(ValueError { raw: v.to_string(), reason: "is not a boolean".to_string() })
+        }
+    }
 }
 
 enum ReadStatus {
@@ -139,9 +219,21 @@ impl ToItem for u32 {
     }
 }
 
-fn panic<T: std::fmt::Display>(msg: T) {
+impl ToItem for f64 {
+    fn to_item(self) -> ItemValue {
+        ItemValue::Single(RcValue::new(RefCell::new(self.to_string())))
+    }
+}
+
+impl ToItem for bool {
+    fn to_item(self) -> ItemValue {
+        ItemValue::Single(RcValue::new(RefCell::new(self.to_string())))
+    }
+}
+
+fn panic<T: std::fmt::Display>(msg: T) -> ! {
     println!("{}", msg);
-    std::process::exit(0);
+    std::process::exit(1);
 }
 
 impl Flag {
@@ -152,12 +244,18 @@ impl Flag {
 
     fn register_with_desc<T: ToItem>(&mut self, key: String, default: T
         , desc: String, value_len: isize) -> Value {
+        self.register_with_conversion(key, default, desc, value_len, None)
+    }
+
+    fn register_with_conversion<T: ToItem>(&mut self, key: String, default: T
+        , desc: String, value_len: isize, conversion: Option<Conversion>) -> Value {
         let r = default.to_item();
         self.keys.insert(key.to_string(), Item{
             value: r.clone(),
             desc: desc,
             is: false,
-            value_len: value_len
+            value_len: value_len,
+            conversion: conversion
         });
         Value::new(r)
     }
@@ -168,8 +266,41 @@ impl Flag {
     }
 
     pub fn reg_u32(&mut self, key: String, default: u32, desc: String) -> Value {
-        self.register_with_desc(key
-            , default, desc, 1)
+        self.register_with_conversion(key
+            , default, desc, 1, Some(Conversion::Integer))
+    }
+
+    pub fn reg_float(&mut self, key: String, default: f64, desc: String) -> Value {
+        self.register_with_conversion(key
+            , default, desc, 1, Some(Conversion::Float))
+    }
+
+    pub fn reg_bool(&mut self, key: String, default: bool, desc: String) -> Value {
+        self.register_with_conversion(key
+            , default, desc, 1, Some(Conversion::Boolean))
+    }
+
+    /// Registers a presence flag (`--verbose`) that takes no value: its
+    /// state is read with `has(key)` rather than through the returned
+    /// `Value`, which stays at its `false` default unless the flag is
+    /// given in `key=value` form.
+    pub fn reg_bool_flag(&mut self, key: String, desc: String) -> Value {
+        self.register_with_conversion(key
+            , false, desc, 0, Some(Conversion::Boolean))
+    }
+
+    /// Registers a timestamp flag. `fmt` is a strftime-style format
+    /// string (e.g. `"%Y-%m-%d"`); pass an empty string to fall back to
+    /// a default RFC3339 parse.
+    pub fn reg_timestamp(&mut self, key: String, default: String, desc: String
+        , fmt: String) -> Value {
+        let conversion = if fmt.is_empty() {
+            Conversion::Timestamp
+        } else {
+            Conversion::TimestampFmt(fmt)
+        };
+        self.register_with_conversion(key
+            , default, desc, 1, Some(conversion))
     }
 
     pub fn reg_fixed_str_vec(&mut self, key: String, default: VecDeque<String>
@@ -195,32 +326,75 @@ impl Flag {
         v.is
     }
 
+    /// Parses `env::args()`, printing the error and exiting with a
+    /// non-zero status on failure. A thin convenience wrapper around
+    /// [`Flag::try_parse`] for binaries that just want to bail out; use
+    /// `try_parse` directly to recover instead.
     pub fn parse(&mut self) {
-        let args = env::args();
+        if let Err(e) = self.try_parse() {
+            Self::exit_on_error(e);
+        }
+    }
+
+    fn exit_on_error(e: ParseError) -> ! {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+
+    /// Parses `env::args()`, returning a [`ParseError`] instead of
+    /// exiting the process so library users can recover.
+    pub fn try_parse(&mut self) -> Result<(), ParseError> {
+        self.try_parse_args(env::args())
+    }
+
+    fn try_parse_args<I: Iterator<Item = String>>(&mut self, args: I) -> Result<(), ParseError> {
         let mut reader: Option<Reader> = None;
         let mut read_status = ReadStatus::Finish;
-        for (i, arg) in args.enumerate() {
+        for arg in args {
             if arg == self.help {
                 self.print_help();
                 self.exit();
             }
-            match self.keys.get(&arg) {
-                Some(item) => {
+            if let Some(eq) = arg.find('=') {
+                let (key, value) = arg.split_at(eq);
+                if self.keys.contains_key(key) {
                     if let Some(r) = &reader {
                         read_status = r.next_key();
                     };
                     if let ReadStatus::Processing = &read_status {
-                        panic(format!(
-                                "the parameters before the {} parameter are not matched"
-                                , arg));
+                        return Err(ParseError::UnmatchedParameterCount { arg });
                     }
-                    reader = Some(Reader::new(item.value.clone()
-                            , item.value_len));
+                    self.set_inline_value(key, &value[1..])?;
+                    reader = None;
                     continue;
-                },
-                None => {
                 }
             }
+            let matched = self.keys.get(&arg).map(|item| (item.value.clone(), item.value_len));
+            if let Some((value, value_len)) = matched {
+                if let Some(r) = &reader {
+                    read_status = r.next_key();
+                };
+                if let ReadStatus::Processing = &read_status {
+                    return Err(ParseError::UnmatchedParameterCount { arg });
+                }
+                self.set_flag_seen(&arg);
+                if value_len == 0 {
+                    reader = None;
+                } else {
+                    reader = Some(Reader::new(value, value_len));
+                }
+                continue;
+            }
+            if self.try_bundled_short_flags(&arg) {
+                if let Some(r) = &reader {
+                    read_status = r.next_key();
+                };
+                if let ReadStatus::Processing = &read_status {
+                    return Err(ParseError::UnmatchedParameterCount { arg });
+                }
+                reader = None;
+                continue;
+            }
             match &mut reader {
                 Some(r) => {
                     read_status = r.process(arg);
@@ -229,9 +403,105 @@ impl Flag {
                     }
                 },
                 None => {
+                    if arg.starts_with('-') && arg.len() > 1 {
+                        return Err(ParseError::UnknownKey { arg });
+                    }
                 }
             }
         }
+        self.validate_conversions()
+    }
+
+    /// Feeds the right-hand side of a `--key=value` token straight into
+    /// the matched flag, bypassing the `Reader`/space-separated path
+    /// entirely since there is exactly one value and it's already in
+    /// hand. Only single-valued flags support this syntax.
+    fn set_inline_value(&mut self, key: &str, value: &str) -> Result<(), ParseError> {
+        let item = self.keys.get_mut(key).expect("key presence already checked by caller");
+        match &item.value {
+            ItemValue::Single(v) => {
+                *v.borrow_mut() = value.to_string();
+            },
+            ItemValue::Multi(_) => {
+                return Err(ParseError::Conversion {
+                    key: key.to_string(),
+                    raw: value.to_string(),
+                    reason: "does not support \"key=value\" syntax".to_string()
+                });
+            }
+        }
+        item.is = true;
+        Ok(())
+    }
+
+    /// Marks a registered key as present. Called as soon as its token
+    /// is matched, whether or not it goes on to read any values, so
+    /// `has()` and conversion validation agree on what the user
+    /// actually passed.
+    fn set_flag_seen(&mut self, key: &str) {
+        if let Some(item) = self.keys.get_mut(key) {
+            item.is = true;
+        }
+    }
+
+    /// Recognizes `-abc` as shorthand for `-a -b -c`: every character
+    /// after the leading dash must already be registered as its own
+    /// short key with `value_len == 0` (see `reg_bool_flag`), or this
+    /// isn't a bundle and the caller should fall back to treating `arg`
+    /// as an ordinary value/unknown token. A bundle member that takes a
+    /// value (e.g. a `reg_string` flag) would otherwise have its value
+    /// silently swallowed, so any such member disqualifies the whole
+    /// bundle.
+    fn try_bundled_short_flags(&mut self, arg: &str) -> bool {
+        if !arg.starts_with('-') || arg.starts_with("--") || arg.len() <= 2 {
+            return false;
+        }
+        let keys: Vec<String> = arg[1..].chars().map(|c| format!("-{}", c)).collect();
+        let all_valueless = keys.iter().all(|key| {
+            self.keys.get(key).map(|item| item.value_len == 0).unwrap_or(false)
+        });
+        if !all_valueless {
+            return false;
+        }
+        for key in keys {
+            self.set_flag_seen(&key);
+        }
+        true
+    }
+
+    /// Runs after all arguments have been consumed: applies each
+    /// registered `Conversion` to the raw text of every flag the user
+    /// actually passed, and reports the offending key and text instead
+    /// of letting a bad value reach read-site unnoticed. Flags the user
+    /// never set are skipped so an untouched default (e.g. `""` for an
+    /// unset timestamp) doesn't have to satisfy the conversion itself.
+    fn validate_conversions(&self) -> Result<(), ParseError> {
+        for (key, item) in self.keys.iter() {
+            if !item.is {
+                continue;
+            }
+            let conversion = match &item.conversion {
+                Some(c) => c,
+                None => continue
+            };
+            match &item.value {
+                ItemValue::Single(v) => {
+                    let raw = v.borrow().clone();
+                    if let Err(reason) = conversion.validate(&raw) {
+                        return Err(ParseError::Conversion { key: key.clone(), raw, reason });
+                    }
+                },
+                ItemValue::Multi(v) => {
+                    for rc in v {
+                        let raw = rc.borrow().clone();
+                        if let Err(reason) = conversion.validate(&raw) {
+                            return Err(ParseError::Conversion { key: key.clone(), raw, reason });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 
     fn warning<T: std::fmt::Display>(&self, msg: T) {
@@ -245,6 +515,12 @@ impl Flag {
         for (key, value) in self.keys.iter() {
             println!("\t{}\n\t\tdefault: {}\n\t\tdesc: {}", key, value.value, &value.desc);
         }
+        for cmd in self.subcommands.values() {
+            println!("subcommand: {}", cmd.name);
+            for (key, value) in cmd.flag.keys.iter() {
+                println!("\t{}\n\t\tdefault: {}\n\t\tdesc: {}", key, value.value, &value.desc);
+            }
+        }
     }
 
     fn exit(&self) {
@@ -267,9 +543,67 @@ impl Flag {
         Self {
             help: "--help".to_string(),
             keys: HashMap::new(),
-            is_warning: true
+            is_warning: true,
+            subcommands: HashMap::new()
         }
     }
+
+    /// Registers (or re-opens) a subcommand and returns its own `Flag`
+    /// so the caller can `reg_*` options on it exactly as on the
+    /// top-level `Flag`.
+    pub fn subcommand(&mut self, name: String) -> &mut Flag {
+        let cmd = self.subcommands.entry(name.clone())
+            .or_insert_with(|| Command::new(name));
+        &mut cmd.flag
+    }
+
+    /// Attaches a handler to a previously registered subcommand; it
+    /// runs once inside `dispatch()` after that subcommand's arguments
+    /// have parsed successfully.
+    pub fn on<F: FnMut(&Flag) + 'static>(&mut self, name: &str, handler: F) {
+        if let Some(cmd) = self.subcommands.get_mut(name) {
+            cmd.handler = Some(Box::new(handler));
+        }
+    }
+
+    /// Entry point for a multi-mode binary: peeks the first
+    /// non-program argument, routes to the matching subcommand's
+    /// `parse()` and handler when one matches, and otherwise falls
+    /// back to parsing the top-level flags (so a binary with no
+    /// subcommands, or one invoked without a verb, behaves exactly like
+    /// plain `parse()`). Prints the error and exits with a non-zero
+    /// status on failure; use `try_dispatch` to recover instead.
+    pub fn dispatch(&mut self) {
+        if let Err(e) = self.try_dispatch() {
+            Self::exit_on_error(e);
+        }
+    }
+
+    /// `Result`-returning version of [`Flag::dispatch`].
+    pub fn try_dispatch(&mut self) -> Result<(), ParseError> {
+        self.try_dispatch_args(env::args())
+    }
+
+    fn try_dispatch_args<I: Iterator<Item = String>>(&mut self, args: I) -> Result<(), ParseError> {
+        let mut args = args;
+        let program = args.next().unwrap_or_default();
+        let first = match args.next() {
+            Some(first) if self.subcommands.contains_key(&first) => first,
+            Some(first) => {
+                return self.try_parse_args(std::iter::once(program)
+                    .chain(std::iter::once(first)).chain(args));
+            },
+            None => {
+                return self.try_parse_args(std::iter::once(program));
+            }
+        };
+        let cmd = self.subcommands.get_mut(&first).unwrap();
+        cmd.flag.try_parse_args(std::iter::once(program).chain(args))?;
+        if let Some(handler) = &mut cmd.handler {
+            handler(&cmd.flag);
+        }
+        Ok(())
+    }
 }
 
 #[macro_export]
@@ -282,13 +616,13 @@ macro_rules! read {
                     Err(_) => {
                         println!("[ERROR] file: {}, line: {}, var \"{}\": to {} error"
                             , file!(), line!(), stringify!($v), stringify!($typ));
-                        std::process::exit(0);
+                        std::process::exit(1);
                     }
                 }
             },
             ItemValue::Multi(_) => {
                 println!("[ERROR] value is single");
-                std::process::exit(0);
+                std::process::exit(1);
             }
         }
     }
@@ -315,7 +649,7 @@ macro_rules! read_string {
             ItemValue::Single(v) => v,
             ItemValue::Multi(_) => {
                 println!("[ERROR] value is single");
-                std::process::exit(0);
+                std::process::exit(1);
             }
         }.borrow()
     }
@@ -336,7 +670,7 @@ macro_rules! read_item {
             Err(_) => {
                 println!("[ERROR] file: {}, line: {}, var \"{}\": to {} error"
                     , file!(), line!(), stringify!($v), stringify!($typ));
-                std::process::exit(0);
+                std::process::exit(1);
             }
         }
     }
@@ -349,7 +683,7 @@ macro_rules! read_vector {
             ItemValue::Multi(v) => v,
             ItemValue::Single(_) => {
                 println!("[ERROR] value is single");
-                std::process::exit(0);
+                std::process::exit(1);
             }
         }
     }
@@ -393,5 +727,124 @@ mod test {
             println!("{}", read_string_item!(item));
         }
     }
+
+    #[test]
+    fn parses_inline_key_value() {
+        let mut flag = Flag::new();
+        let host = flag.reg_string(String::from("--host"), String::from("localhost")
+            , String::from("host"));
+        flag.try_parse_args(vecdeque!["prog".to_string(), "--host=example.com".to_string()]
+            .into_iter()).unwrap();
+        assert_eq!(read_string!(host), "example.com");
+    }
+
+    #[test]
+    fn parses_bundled_short_flags() {
+        let mut flag = Flag::new();
+        flag.reg_bool_flag(String::from("-v"), String::from("verbose"));
+        flag.reg_bool_flag(String::from("-x"), String::from("trace"));
+        flag.try_parse_args(vecdeque!["prog".to_string(), "-vx".to_string()].into_iter())
+            .unwrap();
+        assert!(flag.has("-v"));
+        assert!(flag.has("-x"));
+    }
+
+    #[test]
+    fn bundle_with_value_bearing_member_is_rejected() {
+        let mut flag = Flag::new();
+        let host = flag.reg_string(String::from("-h"), String::from("localhost")
+            , String::from("host"));
+        flag.reg_bool_flag(String::from("-v"), String::from("verbose"));
+        let err = flag.try_parse_args(vecdeque!["prog".to_string(), "-hv".to_string()]
+            .into_iter()).unwrap_err();
+        assert!(matches!(err, ParseError::UnknownKey { arg } if arg == "-hv"));
+        assert!(!flag.has("-v"));
+        assert_eq!(read_string!(host), "localhost");
+    }
+
+    #[test]
+    fn subcommand_parses_into_its_own_flag_and_runs_handler() {
+        let mut flag = Flag::new();
+        let port = {
+            let serve = flag.subcommand(String::from("serve"));
+            serve.reg_u32(String::from("-p"), 80, String::from("port"))
+        };
+        let ran = Rc::new(RefCell::new(false));
+        let ran_clone = ran.clone();
+        flag.on("serve", move |f: &Flag| {
+            assert!(f.has("-p"));
+            *ran_clone.borrow_mut() = true;
+        });
+
+        flag.try_dispatch_args(vecdeque!["prog".to_string(), "serve".to_string()
+            , "-p".to_string(), "9090".to_string()].into_iter()).unwrap();
+
+        assert_eq!(read_u32!(port), 9090);
+        assert!(*ran.borrow());
+    }
+
+    #[test]
+    fn dispatch_falls_back_to_top_level_flags_when_no_subcommand_matches() {
+        let mut flag = Flag::new();
+        let host = flag.reg_string(String::from("-h"), String::from("localhost")
+            , String::from("host"));
+        flag.subcommand(String::from("serve"));
+
+        flag.try_dispatch_args(vecdeque!["prog".to_string(), "-h".to_string()
+            , "example.com".to_string()].into_iter()).unwrap();
+
+        assert_eq!(read_string!(host), "example.com");
+    }
+
+    #[test]
+    fn dispatch_falls_back_to_top_level_flags_with_no_arguments() {
+        let mut flag = Flag::new();
+        flag.subcommand(String::from("serve"));
+
+        flag.try_dispatch_args(vecdeque!["prog".to_string()].into_iter()).unwrap();
+    }
+
+    #[test]
+    fn reg_bool_flag_is_absent_until_passed() {
+        let mut flag = Flag::new();
+        flag.reg_bool_flag(String::from("-q"), String::from("quiet"));
+        flag.try_parse_args(vecdeque!["prog".to_string()].into_iter()).unwrap();
+        assert!(!flag.has("-q"));
+
+        let mut flag = Flag::new();
+        flag.reg_bool_flag(String::from("-q"), String::from("quiet"));
+        flag.try_parse_args(vecdeque!["prog".to_string(), "-q".to_string()].into_iter())
+            .unwrap();
+        assert!(flag.has("-q"));
+    }
+
+    #[test]
+    fn reports_unmatched_parameter_count() {
+        let mut flag = Flag::new();
+        flag.reg_string(String::from("-h"), String::from("localhost"), String::from("host"));
+        flag.reg_string(String::from("-u"), String::from(""), String::from("user"));
+        let err = flag.try_parse_args(vecdeque!["prog".to_string(), "-h".to_string()
+            , "-u".to_string()].into_iter()).unwrap_err();
+        assert!(matches!(err, ParseError::UnmatchedParameterCount { arg } if arg == "-u"));
+    }
+
+    #[test]
+    fn reports_unknown_key() {
+        let mut flag = Flag::new();
+        flag.reg_string(String::from("-h"), String::from("localhost"), String::from("host"));
+        let err = flag.try_parse_args(vecdeque!["prog".to_string(), "--nope".to_string()]
+            .into_iter()).unwrap_err();
+        assert!(matches!(err, ParseError::UnknownKey { arg } if arg == "--nope"));
+    }
+
+    #[test]
+    fn reports_conversion_error() {
+        let mut flag = Flag::new();
+        flag.reg_u32(String::from("-p"), 80, String::from("port"));
+        let err = flag.try_parse_args(vecdeque!["prog".to_string(), "-p".to_string()
+            , "notanumber".to_string()].into_iter()).unwrap_err();
+        assert!(matches!(err, ParseError::Conversion { key, raw, .. }
+            if key == "-p" && raw == "notanumber"));
+    }
 }
 